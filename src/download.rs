@@ -0,0 +1,239 @@
+use std::{cmp::min, fs, fs::File, io, io::Write, path::PathBuf, sync::atomic::{AtomicU64, Ordering}, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use futures_util::{future::join_all, StreamExt};
+use log::{info, warn};
+use reqwest::{header::ACCEPT_RANGES, Client, Url};
+
+use crate::progress::{Progress, ProgressSink, Stage};
+
+/// Minimum size of each ranged chunk; a body under twice this size is
+/// fetched as a single ranged chunk rather than split further.
+const MIN_CHUNK_SIZE: u64 = 4 * 1024 * 1024;
+/// Upper bound on how many concurrent range requests we issue for one file.
+const MAX_CHUNKS: u64 = 8;
+
+const MAX_RETRIES: u32 = 5;
+const BASE_RETRY_DELAY_MS: u64 = 200;
+const MAX_RETRY_DELAY_MS: u64 = 5_000;
+
+struct RangeProbe {
+    supports_ranges: bool,
+    total_size: Option<u64>,
+}
+
+/// Download `url` into `path`, splitting the body into concurrent
+/// `Range`-request chunks when the server advertises support for them, and
+/// falling back to a single streamed `GET` otherwise. Progress is reported
+/// to `sink` as bytes arrive.
+pub async fn download_video(client: &Client, url: &str, path: &PathBuf, sink: &dyn ProgressSink) -> Result<String, String> {
+    info!("downloading video");
+    let u = Url::parse(url).unwrap();
+    let file_name = u.path().split("/").last().unwrap();
+    let mut saved_path = PathBuf::from(path);
+    saved_path.push(file_name);
+
+    if saved_path.exists() {
+        return Ok(String::from(file_name));
+    }
+
+    let probe = probe_range_support(client, url).await?;
+    let total_size = probe.total_size.unwrap_or(0);
+    sink.report(Progress { stage: Stage::Video, downloaded: 0, total: total_size });
+
+    match probe {
+        RangeProbe { supports_ranges: true, total_size: Some(total_size) } if total_size > 0 => {
+            download_segmented(client, url, &saved_path, total_size, sink).await?;
+        }
+        _ => {
+            download_sequential(client, url, &saved_path, sink).await?;
+        }
+    }
+
+    info!("downloaded {} to {}", url, saved_path.to_str().unwrap());
+    Ok(String::from(file_name))
+}
+
+async fn probe_range_support(client: &Client, url: &str) -> Result<RangeProbe, String> {
+    let res = client.head(url).send().await.or(Err(format!("Failed to HEAD '{}'", url)))?;
+    let supports_ranges = res.headers()
+        .get(ACCEPT_RANGES)
+        .map_or(false, |value| value.as_bytes() == b"bytes");
+    let total_size = res.content_length();
+    info!("range probe: supports_ranges={} total_size={:?}", supports_ranges, total_size);
+    Ok(RangeProbe { supports_ranges, total_size })
+}
+
+async fn download_sequential(client: &Client, url: &str, saved_path: &PathBuf, sink: &dyn ProgressSink) -> Result<(), String> {
+    let res = client.get(url).send().await.or(Err(format!("Failed to GET from '{}'", &url)))?;
+    let total_size = res.content_length().unwrap_or(0);
+
+    let mut file = File::create(saved_path).or(Err(format!("Failed to create file '{}'", saved_path.to_str().unwrap())))?;
+    let mut downloaded: u64 = 0;
+    let mut stream = res.bytes_stream();
+
+    while let Some(item) = stream.next().await {
+        let chunk = item.or(Err(String::from("Error while downloading file")))?;
+        file.write_all(&chunk).or(Err(String::from("Error while writing to file")))?;
+        downloaded = min(downloaded + (chunk.len() as u64), total_size);
+        sink.report(Progress { stage: Stage::Video, downloaded, total: total_size });
+    }
+
+    Ok(())
+}
+
+/// How many concurrent chunks to split a body of `total_size` bytes into,
+/// and the size of each.
+fn chunk_plan(total_size: u64) -> (u64, u64) {
+    let chunk_count = (total_size / MIN_CHUNK_SIZE).max(1).min(MAX_CHUNKS);
+    let chunk_size = total_size / chunk_count;
+    (chunk_count, chunk_size)
+}
+
+async fn download_segmented(client: &Client, url: &str, saved_path: &PathBuf, total_size: u64, sink: &dyn ProgressSink) -> Result<(), String> {
+    let (chunk_count, chunk_size) = chunk_plan(total_size);
+    let downloaded = AtomicU64::new(0);
+
+    let mut part_paths = Vec::with_capacity(chunk_count as usize);
+    let mut downloads = Vec::with_capacity(chunk_count as usize);
+
+    for i in 0..chunk_count {
+        let start = i * chunk_size;
+        let end = if i == chunk_count - 1 { total_size - 1 } else { start + chunk_size - 1 };
+
+        let mut part_path = saved_path.clone();
+        part_path.set_extension(format!("part{}", i));
+        part_paths.push(part_path.clone());
+
+        downloads.push(download_chunk_with_retry(client, url, start, end, part_path, total_size, &downloaded, sink));
+    }
+
+    for result in join_all(downloads).await {
+        result?;
+    }
+
+    let mut file = File::create(saved_path).or(Err(format!("Failed to create file '{}'", saved_path.to_str().unwrap())))?;
+    for part_path in &part_paths {
+        let mut part = File::open(part_path).or(Err(format!("Failed to open part file '{}'", part_path.to_str().unwrap())))?;
+        io::copy(&mut part, &mut file).or(Err(String::from("Error while assembling downloaded parts")))?;
+        let _ = fs::remove_file(part_path);
+    }
+
+    Ok(())
+}
+
+async fn download_chunk_with_retry(client: &Client, url: &str, start: u64, end: u64, part_path: PathBuf, total_size: u64, downloaded: &AtomicU64, sink: &dyn ProgressSink) -> Result<(), String> {
+    let mut attempt = 0;
+    loop {
+        match download_chunk(client, url, start, end, &part_path, total_size, downloaded, sink).await {
+            Ok(()) => return Ok(()),
+            Err(err) if attempt < MAX_RETRIES => {
+                attempt += 1;
+                let delay = retry_delay(attempt);
+                warn!("chunk {}-{} failed ({}), retrying in {:?} (attempt {}/{})", start, end, err, delay, attempt, MAX_RETRIES);
+                tokio::time::sleep(delay).await;
+            }
+            Err(err) => return Err(format!("chunk {}-{} failed after {} attempts: {}", start, end, MAX_RETRIES + 1, err)),
+        }
+    }
+}
+
+async fn download_chunk(client: &Client, url: &str, start: u64, end: u64, part_path: &PathBuf, total_size: u64, downloaded: &AtomicU64, sink: &dyn ProgressSink) -> Result<(), String> {
+    let res = client.get(url)
+        .header("Range", format!("bytes={}-{}", start, end))
+        .send().await
+        .or(Err(format!("Failed to GET range '{}-{}' from '{}'", start, end, url)))?;
+
+    let mut file = File::create(part_path).or(Err(format!("Failed to create part file '{}'", part_path.to_str().unwrap())))?;
+    let mut stream = res.bytes_stream();
+
+    // Bytes are folded into the shared `downloaded` counter as they arrive,
+    // same as before, so progress across all concurrently-downloading
+    // chunks stays live and monotonic. But if this attempt fails partway
+    // through, its bytes are backed back out of the counter before the
+    // error is returned, so a retry that redownloads them from scratch
+    // doesn't leave them double-counted.
+    let mut chunk_downloaded: u64 = 0;
+
+    loop {
+        match stream.next().await {
+            Some(Ok(chunk)) => {
+                if file.write_all(&chunk).is_err() {
+                    let so_far = back_out_progress(downloaded, chunk_downloaded);
+                    sink.report(Progress { stage: Stage::Video, downloaded: so_far, total: total_size });
+                    return Err(String::from("Error while writing chunk"));
+                }
+                chunk_downloaded += chunk.len() as u64;
+                let so_far = record_progress(downloaded, chunk.len() as u64);
+                sink.report(Progress { stage: Stage::Video, downloaded: so_far, total: total_size });
+            }
+            Some(Err(_)) => {
+                let so_far = back_out_progress(downloaded, chunk_downloaded);
+                sink.report(Progress { stage: Stage::Video, downloaded: so_far, total: total_size });
+                return Err(String::from("Error while downloading chunk"));
+            }
+            None => break,
+        }
+    }
+
+    Ok(())
+}
+
+/// Add `len` bytes to the shared progress counter and return the new total.
+fn record_progress(downloaded: &AtomicU64, len: u64) -> u64 {
+    downloaded.fetch_add(len, Ordering::SeqCst) + len
+}
+
+/// Remove `attempt_bytes` from the shared progress counter - used when a
+/// chunk attempt fails partway through, so its bytes don't outlive the
+/// part file they were written to before being retried from scratch.
+fn back_out_progress(downloaded: &AtomicU64, attempt_bytes: u64) -> u64 {
+    downloaded.fetch_sub(attempt_bytes, Ordering::SeqCst) - attempt_bytes
+}
+
+/// Exponential backoff with full jitter: doubles the base delay per attempt
+/// up to a cap, then picks a random point between zero and that cap.
+fn retry_delay(attempt: u32) -> Duration {
+    let capped = BASE_RETRY_DELAY_MS.saturating_mul(1 << attempt.min(16)).min(MAX_RETRY_DELAY_MS);
+    let jitter = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().subsec_nanos() as u64 % (capped + 1);
+    Duration::from_millis(jitter)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_delay_grows_with_attempt_and_caps_at_max() {
+        assert!(retry_delay(0) <= Duration::from_millis(BASE_RETRY_DELAY_MS));
+        assert!(retry_delay(1) <= Duration::from_millis(BASE_RETRY_DELAY_MS * 2));
+
+        for attempt in 0..20 {
+            assert!(retry_delay(attempt) <= Duration::from_millis(MAX_RETRY_DELAY_MS));
+        }
+    }
+
+    #[test]
+    fn chunk_plan_splits_large_bodies_up_to_max_chunks() {
+        assert_eq!(chunk_plan(1), (1, 1));
+        assert_eq!(chunk_plan(MIN_CHUNK_SIZE - 1), (1, MIN_CHUNK_SIZE - 1));
+        assert_eq!(chunk_plan(MIN_CHUNK_SIZE * 3), (3, MIN_CHUNK_SIZE));
+
+        let (count, size) = chunk_plan(MIN_CHUNK_SIZE * MAX_CHUNKS * 10);
+        assert_eq!(count, MAX_CHUNKS);
+        assert_eq!(size, MIN_CHUNK_SIZE * 10);
+    }
+
+    #[test]
+    fn failed_attempt_backs_its_bytes_out_without_touching_others() {
+        let downloaded = AtomicU64::new(0);
+
+        // Another chunk has already committed 100 bytes.
+        assert_eq!(record_progress(&downloaded, 100), 100);
+
+        // This chunk streams 50 bytes, then fails and backs them back out.
+        assert_eq!(record_progress(&downloaded, 50), 150);
+        assert_eq!(back_out_progress(&downloaded, 50), 100);
+
+        assert_eq!(downloaded.load(Ordering::SeqCst), 100);
+    }
+}
@@ -0,0 +1,50 @@
+use reqwest::Url;
+use scraper::Html;
+
+use crate::{extractors, format::Format};
+
+/// A site-specific strategy for pulling a video link and transcript out of a
+/// talk page's HTML.
+///
+/// Adding support for a new host means writing one new module that
+/// implements this trait and registering it in [`registry`], rather than
+/// editing the dispatch logic in `main`.
+pub trait Extractor {
+    /// Whether this extractor knows how to handle `url`.
+    fn matches(&self, url: &Url) -> bool;
+
+    /// Enumerate the downloadable video renditions a page offers.
+    fn formats(&self, html: &Html) -> Vec<Format>;
+
+    /// Resolve a DASH (`.mpd`) or HLS (`.m3u8`) manifest link, for sites
+    /// that serve adaptive streams instead of a direct video file. Defaults
+    /// to none, since most sites still expose direct links through `formats`.
+    fn manifest_link(&self, _html: &Html) -> Option<String> {
+        None
+    }
+
+    /// Pull the transcript out as `(start_ms, text)` pairs, in order.
+    fn transcript(&self, html: &Html) -> Vec<(i64, String)>;
+
+    /// Collect the talk URLs linked from a playlist / course index page.
+    /// Pages that aren't playlists simply have none, so this defaults to
+    /// empty rather than being required of every extractor.
+    fn playlist(&self, _html: &Html) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+/// The extractors this crate knows about, tried in order against the
+/// request URL. The first match wins.
+pub fn registry() -> Vec<Box<dyn Extractor>> {
+    vec![Box::new(extractors::default_site::DefaultSite)]
+}
+
+/// Find the extractor that handles `url`, falling back to the default site
+/// so existing links keep working unchanged.
+pub fn for_url(url: &Url) -> Box<dyn Extractor> {
+    registry()
+        .into_iter()
+        .find(|extractor| extractor.matches(url))
+        .unwrap_or_else(|| Box::new(extractors::default_site::DefaultSite))
+}
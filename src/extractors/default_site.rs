@@ -0,0 +1,73 @@
+use log::info;
+use reqwest::Url;
+use scraper::{ElementRef, Html, Selector};
+
+use crate::{extractor::Extractor, format, format::Format, manifest};
+
+/// The original talk site this crate was written against. Matches any URL,
+/// so it also acts as the fallback when no other extractor claims the page.
+pub struct DefaultSite;
+
+impl Extractor for DefaultSite {
+    fn matches(&self, _url: &Url) -> bool {
+        true
+    }
+
+    fn formats(&self, html: &Html) -> Vec<Format> {
+        info!("parsing formats");
+        let video_selector = Selector::parse(r#"li.download ul li a"#).unwrap();
+        html.select(&video_selector)
+            .filter_map(|a_el| {
+                let href = a_el.value().attr("href")?;
+                let label = a_el.inner_html();
+                info!("found format {}: {}", label, href);
+                Some(Format {
+                    height: format::height_from_label(&label),
+                    label,
+                    href: String::from(href),
+                })
+            })
+            .collect()
+    }
+
+    fn transcript(&self, html: &Html) -> Vec<(i64, String)> {
+        info!("parsing transcript");
+        let mut entries = vec![];
+        let p_selector = Selector::parse(r#"li.supplement.transcript p"#).unwrap();
+        let sentence_selector = Selector::parse("span.sentence").unwrap();
+
+        for p_element in html.select(&p_selector) {
+            for element in p_element.select(&sentence_selector) {
+                let span_node = element.first_child().unwrap();
+                let span_element = ElementRef::wrap(span_node).unwrap();
+                let time_str = span_element.value().attr("data-start").unwrap();
+                let time_float: f64 = time_str
+                    .parse()
+                    .unwrap_or_else(|_| panic!("{} is not a digit", time_str));
+                let time: i64 = (time_float * 1000.0) as i64;
+                let text = span_element.inner_html().to_string();
+                info!("{}:{}", time, text);
+
+                entries.push((time, text));
+            }
+        }
+
+        entries
+    }
+
+    fn manifest_link(&self, html: &Html) -> Option<String> {
+        self.formats(html)
+            .into_iter()
+            .find(|f| manifest::is_manifest(&f.href))
+            .map(|f| f.href)
+    }
+
+    fn playlist(&self, html: &Html) -> Vec<String> {
+        info!("parsing playlist");
+        let link_selector = Selector::parse(r#"li.talk-link a"#).unwrap();
+        html.select(&link_selector)
+            .filter_map(|a_el| a_el.value().attr("href"))
+            .map(String::from)
+            .collect()
+    }
+}
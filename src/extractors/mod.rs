@@ -0,0 +1 @@
+pub mod default_site;
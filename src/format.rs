@@ -0,0 +1,62 @@
+use std::str::FromStr;
+
+/// One concrete, downloadable rendition of a talk's video, as advertised by
+/// the site — e.g. "HD", "SD", or an explicit "1080p" link.
+#[derive(Debug, Clone)]
+pub struct Format {
+    pub label: String,
+    pub height: Option<u32>,
+    pub href: String,
+}
+
+/// A user-requested quality, resolved against whatever `Format`s a page
+/// actually offers.
+#[derive(Debug, Clone, Copy)]
+pub enum Quality {
+    Best,
+    Worst,
+    Height(u32),
+}
+
+impl FromStr for Quality {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "best" => Ok(Quality::Best),
+            "worst" => Ok(Quality::Worst),
+            other => other.parse::<u32>()
+                .map(Quality::Height)
+                .map_err(|_| format!("invalid quality '{}': expected best, worst, or a height like 1080", other)),
+        }
+    }
+}
+
+impl Quality {
+    /// Pick the `Format` that best matches this quality, if any were offered.
+    pub fn select<'a>(&self, formats: &'a [Format]) -> Option<&'a Format> {
+        match self {
+            Quality::Best => formats.iter().max_by_key(|f| f.height.unwrap_or(0)),
+            Quality::Worst => formats.iter().min_by_key(|f| f.height.unwrap_or(u32::MAX)),
+            Quality::Height(target) => formats.iter()
+                .min_by_key(|f| (f.height.unwrap_or(0) as i64 - *target as i64).abs()),
+        }
+    }
+}
+
+/// Best-effort height extraction from a format label like "1080p" or
+/// "720p60"; falls back to a rough guess for the site's old literal
+/// "HD"/"SD" labels, and `None` otherwise.
+pub fn height_from_label(label: &str) -> Option<u32> {
+    let digits: String = label.chars().take_while(|c| c.is_ascii_digit()).collect();
+    if !digits.is_empty() {
+        return digits.parse().ok();
+    }
+    if label.contains("HD") {
+        Some(720)
+    } else if label.contains("SD") {
+        Some(480)
+    } else {
+        None
+    }
+}
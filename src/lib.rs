@@ -0,0 +1,193 @@
+pub mod download;
+pub mod extractor;
+pub mod extractors;
+pub mod format;
+pub mod manifest;
+pub mod progress;
+pub mod subtitle;
+
+use anyhow::Result;
+use log::info;
+use reqwest::{header::USER_AGENT, Client, Url};
+use scraper::Html;
+use std::{fs, path::PathBuf, process::{Command, Stdio}};
+
+pub use extractor::Extractor;
+pub use format::Quality;
+pub use progress::{NoopProgress, Progress, ProgressSink, Stage};
+pub use subtitle::{Chapter, SubFormat};
+
+/// Options controlling a single-talk download.
+pub struct Options {
+    pub quality: Quality,
+    pub sub_format: SubFormat,
+    /// How long, in milliseconds, the final subtitle cue stays open past
+    /// its start time when there's no next cue to derive an end from.
+    pub trailing_cue_ms: i64,
+    /// Derive chapter markers from the transcript and embed them in the
+    /// muxed output.
+    pub chapters: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            quality: Quality::Best,
+            sub_format: SubFormat::default(),
+            trailing_cue_ms: subtitle::default_trailing_cue_ms(),
+            chapters: false,
+        }
+    }
+}
+
+/// Fetch `url`, download its video (direct or via a DASH/HLS manifest) at
+/// the requested quality, generate its subtitle file, and mux it in, all
+/// under `path`. Reports progress to `sink` as it goes.
+pub async fn download_talk(client: &Client, url: &str, path: &PathBuf, options: &Options, sink: &dyn ProgressSink) -> Result<()> {
+    let html = fetch_html(client, url).await?;
+    let request_url = Url::parse(url)?;
+    let site = extractor::for_url(&request_url);
+    download_talk_html(client, site.as_ref(), &html, path, options, sink).await
+}
+
+/// GET `url` with a desktop-browser user agent, log the response status,
+/// and parse the body as HTML.
+pub async fn fetch_html(client: &Client, url: &str) -> Result<Html> {
+    let res = client.get(url)
+    .header(USER_AGENT, "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/101.0.4951.64 Safari/537.36")
+    .send().await?;
+
+    info!("Response: {:?} {}", res.version(), res.status());
+
+    let body = res.text().await?;
+    Ok(Html::parse_document(&body))
+}
+
+/// Same as [`download_talk`], but for a page that's already been fetched
+/// and matched to its `Extractor` — lets a caller holding a playlist reuse
+/// the same extractor across its talks without re-resolving it each time.
+pub async fn download_talk_html(client: &Client, site: &dyn Extractor, html: &Html, path: &PathBuf, options: &Options, sink: &dyn ProgressSink) -> Result<()> {
+    let video_name = if let Some(manifest_url) = site.manifest_link(html) {
+        manifest::download_manifest(client, &manifest_url, path, &options.quality, sink).await.map_err(|e| anyhow::anyhow!(e))?
+    } else {
+        let formats = site.formats(html);
+        let href = options.quality.select(&formats).map(|f| f.href.clone()).unwrap_or_default();
+        download::download_video(client, &href, path, sink).await.map_err(|e| anyhow::anyhow!(e))?
+    };
+
+    let subtitle_name = video_name.split(".").nth(0).unwrap().to_owned() + "." + options.sub_format.extension();
+    let mut subtitle_path = PathBuf::from(path);
+    subtitle_path.push(&subtitle_name);
+
+    let transcript = site.transcript(html);
+    let times: Vec<i64> = transcript.iter().map(|(time, _)| *time).collect();
+    let texts: Vec<String> = transcript.into_iter().map(|(_, text)| text).collect();
+    subtitle::generate_subtitle_file(&subtitle_path, &times, &texts, options.sub_format, options.trailing_cue_ms);
+
+    let chapters = if options.chapters {
+        subtitle::derive_chapters(&times, &texts, options.trailing_cue_ms)
+    } else {
+        Vec::new()
+    };
+
+    embed_subtitle(&video_name, &subtitle_name, &chapters, path);
+    Ok(())
+}
+
+/// Mux `subtitle_name` (and, if given, `chapters`) into `video_name` with
+/// ffmpeg, writing `<video_name stem>.out.mp4` under `path` so a playlist
+/// run doesn't have every talk clobber the same output file.
+pub fn embed_subtitle(video_name: &str, subtitle_name: &str, chapters: &[Chapter], path: &PathBuf) {
+    info!("embeding subtitle");
+
+    let metadata_path = if chapters.is_empty() {
+        None
+    } else {
+        let mut metadata_path = PathBuf::from(path);
+        metadata_path.push("chapters.ffmetadata");
+        fs::write(&metadata_path, render_ffmetadata(chapters)).expect("Unable to write chapter metadata");
+        Some(metadata_path)
+    };
+
+    let mut video_path = PathBuf::from(path);
+    video_path.push(video_name);
+    let mut subtitle_path = PathBuf::from(path);
+    subtitle_path.push(subtitle_name);
+
+    let output_name = video_name.split(".").next().unwrap().to_owned() + ".out.mp4";
+    let mut output_path = PathBuf::from(path);
+    output_path.push(&output_name);
+
+    let mut command = Command::new("ffmpeg");
+    command
+        // Overwrite file if it already exists
+        .arg("-y")
+        // Get the data from stdin
+        .arg("-i")
+        .arg(&video_path)
+        .arg("-i")
+        .arg(&subtitle_path);
+
+    if let Some(metadata_path) = &metadata_path {
+        command.arg("-i").arg(metadata_path).arg("-map_metadata").arg("2");
+    }
+
+    let child = command
+        .arg("-map")
+        .arg("0")
+        .arg("-map")
+        .arg("1")
+        .arg("-c:v")
+        .arg("copy")
+        .arg("-c:a")
+        .arg("copy")
+        .arg("-c:s")
+        .arg("mov_text")
+        .arg("-metadata:s:s:0")
+        .arg("language=eng")
+        // Output file, named after the source video so a playlist run
+        // doesn't have every talk clobber the same output.mp4
+        .arg(&output_path)
+        // stdin, stderr, and stdout are piped
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .stdout(Stdio::piped())
+        // Run the child command
+        .spawn()
+        .unwrap();
+
+    let output = child.wait_with_output().unwrap();
+    info!("{}", String::from_utf8(output.stdout).unwrap());
+    info!("{}", String::from_utf8(output.stderr).unwrap());
+    info!("status: {}", output.status);
+
+    if let Some(metadata_path) = metadata_path {
+        let _ = fs::remove_file(metadata_path);
+    }
+}
+
+fn render_ffmetadata(chapters: &[Chapter]) -> String {
+    let mut data = String::from(";FFMETADATA1\n");
+    for chapter in chapters {
+        data.push_str("[CHAPTER]\n");
+        data.push_str("TIMEBASE=1/1000\n");
+        data.push_str(&format!("START={}\n", chapter.start_ms));
+        data.push_str(&format!("END={}\n", chapter.end_ms));
+        data.push_str(&format!("title={}\n", escape_ffmetadata(&chapter.title)));
+    }
+    data
+}
+
+/// Backslash-escape the characters FFMETADATA1 treats specially in a
+/// value (`=`, `;`, `#`, `\`) and flatten newlines, so transcript text
+/// can't be mistaken for key/value syntax or a comment.
+fn escape_ffmetadata(value: &str) -> String {
+    value
+        .chars()
+        .flat_map(|c| match c {
+            '=' | ';' | '#' | '\\' => vec!['\\', c],
+            '\n' | '\r' => vec![' '],
+            other => vec![other],
+        })
+        .collect()
+}
@@ -0,0 +1,376 @@
+use std::{fs, fs::File, io::Write, path::PathBuf, process::{Command, Stdio}};
+
+use log::{info, warn};
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use reqwest::{Client, Url};
+
+use crate::{format::Quality, progress::{Progress, ProgressSink, Stage}};
+
+/// Whether `url` points at a DASH or HLS manifest rather than a direct
+/// media file.
+pub fn is_manifest(url: &str) -> bool {
+    url.ends_with(".mpd") || url.ends_with(".m3u8")
+}
+
+/// Download the video and audio referenced by a DASH (`.mpd`) or HLS
+/// (`.m3u8`) manifest, mux them together with ffmpeg, and return the muxed
+/// file name (relative to `path`). Progress is reported to `sink` as
+/// segments are fetched and again while ffmpeg muxes.
+pub async fn download_manifest(client: &Client, manifest_url: &str, path: &PathBuf, quality: &Quality, sink: &dyn ProgressSink) -> Result<String, String> {
+    if manifest_url.ends_with(".m3u8") {
+        download_hls(client, manifest_url, path, sink).await
+    } else {
+        download_dash(client, manifest_url, path, quality, sink).await
+    }
+}
+
+struct Representation {
+    bandwidth: u64,
+    height: Option<u32>,
+    mime_type: String,
+    media_template: String,
+    init_template: Option<String>,
+    start_number: u64,
+    segment_count: u64,
+}
+
+async fn download_dash(client: &Client, manifest_url: &str, path: &PathBuf, quality: &Quality, sink: &dyn ProgressSink) -> Result<String, String> {
+    info!("downloading dash manifest {}", manifest_url);
+    let base = Url::parse(manifest_url).or(Err(format!("Invalid manifest url '{}'", manifest_url)))?;
+    let body = client.get(manifest_url).send().await.or(Err(format!("Failed to GET '{}'", manifest_url)))?
+        .text().await.or(Err(String::from("Failed to read manifest body")))?;
+
+    let representations = parse_mpd(&body)?;
+    let (video, audio) = pick_representations(representations, quality)?;
+    let total_segments = (video.segment_count + audio.segment_count) as u64;
+
+    let downloaded = std::sync::atomic::AtomicU64::new(0);
+    let video_path = download_representation(client, &base, &video, path, "video", total_segments, &downloaded, sink).await?;
+    let audio_path = download_representation(client, &base, &audio, path, "audio", total_segments, &downloaded, sink).await?;
+
+    sink.report(Progress { stage: Stage::Muxing, downloaded: 0, total: 0 });
+    let muxed = mux_av(&video_path, &audio_path, path)?;
+    sink.report(Progress { stage: Stage::Muxing, downloaded: 1, total: 1 });
+    Ok(muxed)
+}
+
+/// Walk the `MPD` for `AdaptationSet`/`Representation`/`SegmentTemplate`
+/// elements, collecting enough of each representation to build its segment
+/// list. Good enough for the `$Number$`-based templates talk platforms use;
+/// `$Time$` templates aren't handled.
+///
+/// Template/segment-count state lives only for the duration of the
+/// `Representation` it was read from, and a representation is only
+/// finalized on its closing tag, once its `SegmentTemplate`/`SegmentTimeline`
+/// children have actually been parsed - otherwise it'd end up with another
+/// representation's leftover template and an accumulated segment count.
+fn parse_mpd(body: &str) -> Result<Vec<Representation>, String> {
+    let mut reader = Reader::from_str(body);
+    reader.trim_text(true);
+
+    let mut representations = vec![];
+    let mut adaptation_mime = String::new();
+    let mut in_representation = false;
+    let mut current_mime = String::new();
+    let mut current_bandwidth: u64 = 0;
+    let mut current_height: Option<u32> = None;
+    let mut current_media: Option<String> = None;
+    let mut current_init: Option<String> = None;
+    let mut start_number: u64 = 1;
+    let mut segment_count: u64 = 0;
+    let mut buf = Vec::new();
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(ref e)) | Ok(Event::Empty(ref e)) => {
+                match e.name().as_ref() {
+                    b"AdaptationSet" => {
+                        if let Some(mime) = attr(e, b"mimeType") {
+                            adaptation_mime = mime;
+                        }
+                    }
+                    b"Representation" => {
+                        in_representation = true;
+                        current_mime = attr(e, b"mimeType").unwrap_or_else(|| adaptation_mime.clone());
+                        current_bandwidth = attr(e, b"bandwidth").and_then(|b| b.parse().ok()).unwrap_or(0);
+                        current_height = attr(e, b"height").and_then(|h| h.parse().ok());
+                        current_media = None;
+                        current_init = None;
+                        start_number = 1;
+                        segment_count = 0;
+                    }
+                    b"SegmentTemplate" if in_representation => {
+                        current_media = attr(e, b"media");
+                        current_init = attr(e, b"initialization");
+                        if let Some(n) = attr(e, b"startNumber") {
+                            start_number = n.parse().unwrap_or(1);
+                        }
+                    }
+                    b"S" if in_representation => {
+                        let repeat = attr(e, b"r").and_then(|r| r.parse::<u64>().ok()).unwrap_or(0);
+                        segment_count += 1 + repeat;
+                    }
+                    _ => {}
+                }
+            }
+            Ok(Event::End(ref e)) => {
+                if e.name().as_ref() == b"Representation" {
+                    if let Some(media) = current_media.take() {
+                        representations.push(Representation {
+                            bandwidth: current_bandwidth,
+                            height: current_height,
+                            mime_type: current_mime.clone(),
+                            media_template: media,
+                            init_template: current_init.take(),
+                            start_number,
+                            segment_count: segment_count.max(1),
+                        });
+                    }
+                    in_representation = false;
+                }
+            }
+            Ok(Event::Eof) => break,
+            Err(err) => return Err(format!("Failed to parse manifest: {}", err)),
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    Ok(representations)
+}
+
+fn attr(e: &quick_xml::events::BytesStart, name: &[u8]) -> Option<String> {
+    e.attributes()
+        .flatten()
+        .find(|a| a.key.as_ref() == name)
+        .map(|a| String::from_utf8_lossy(&a.value).into_owned())
+}
+
+fn pick_representations(representations: Vec<Representation>, quality: &Quality) -> Result<(Representation, Representation), String> {
+    let (audio_reps, video_reps): (Vec<Representation>, Vec<Representation>) = representations.into_iter()
+        .partition(|r| r.mime_type.starts_with("audio"));
+
+    // Highest-bandwidth audio representation, the same "best" rule video
+    // falls back to below.
+    let audio = audio_reps.into_iter().max_by_key(|r| r.bandwidth)
+        .ok_or(String::from("No audio representation found in manifest"))?;
+
+    let mut video_reps: Vec<Representation> = video_reps.into_iter()
+        .filter(|r| r.mime_type.starts_with("video"))
+        .collect();
+    if video_reps.is_empty() {
+        return Err(String::from("No video representation found in manifest"));
+    }
+    video_reps.sort_by_key(|r| r.bandwidth);
+
+    let video = match quality {
+        Quality::Best => video_reps.pop().unwrap(),
+        Quality::Worst => video_reps.remove(0),
+        Quality::Height(target) => video_reps.into_iter()
+            .min_by_key(|r| (r.height.unwrap_or(0) as i64 - *target as i64).abs())
+            .unwrap(),
+    };
+
+    Ok((video, audio))
+}
+
+async fn download_representation(client: &Client, base: &Url, rep: &Representation, path: &PathBuf, label: &str, total_segments: u64, downloaded: &std::sync::atomic::AtomicU64, sink: &dyn ProgressSink) -> Result<PathBuf, String> {
+    let mut out_path = PathBuf::from(path);
+    out_path.push(format!("{}.{}.m4s", label, rep.bandwidth));
+    let mut file = File::create(&out_path).or(Err(format!("Failed to create '{}'", out_path.to_str().unwrap())))?;
+
+    if let Some(init_template) = &rep.init_template {
+        let init_url = base.join(init_template).or(Err(String::from("Invalid initialization url")))?;
+        let bytes = client.get(init_url).send().await.or(Err(String::from("Failed to GET init segment")))?
+            .bytes().await.or(Err(String::from("Failed to read init segment")))?;
+        file.write_all(&bytes).or(Err(String::from("Failed to write init segment")))?;
+    }
+
+    for number in rep.start_number..(rep.start_number + rep.segment_count) {
+        let segment_path = rep.media_template.replace("$Number$", &number.to_string());
+        let segment_url = base.join(&segment_path).or(Err(format!("Invalid segment url '{}'", segment_path)))?;
+        let bytes = client.get(segment_url).send().await.or(Err(format!("Failed to GET segment {}", number)))?
+            .bytes().await.or(Err(format!("Failed to read segment {}", number)))?;
+        file.write_all(&bytes).or(Err(String::from("Failed to write segment")))?;
+
+        let so_far = downloaded.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+        sink.report(Progress { stage: Stage::Video, downloaded: so_far, total: total_segments });
+    }
+
+    Ok(out_path)
+}
+
+async fn download_hls(client: &Client, manifest_url: &str, path: &PathBuf, sink: &dyn ProgressSink) -> Result<String, String> {
+    info!("downloading hls manifest {}", manifest_url);
+    let base = Url::parse(manifest_url).or(Err(format!("Invalid manifest url '{}'", manifest_url)))?;
+    let master = client.get(manifest_url).send().await.or(Err(format!("Failed to GET '{}'", manifest_url)))?
+        .text().await.or(Err(String::from("Failed to read manifest body")))?;
+
+    let media_playlist_url = select_variant(&base, &master)?;
+    let media_playlist = client.get(media_playlist_url.clone()).send().await.or(Err(String::from("Failed to GET media playlist")))?
+        .text().await.or(Err(String::from("Failed to read media playlist")))?;
+
+    let mut out_path = PathBuf::from(path);
+    out_path.push("stream.ts");
+    let mut file = File::create(&out_path).or(Err(format!("Failed to create '{}'", out_path.to_str().unwrap())))?;
+
+    let segment_urls: Vec<&str> = media_playlist.lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect();
+    let total_segments = segment_urls.len() as u64;
+
+    for (i, line) in segment_urls.iter().enumerate() {
+        let segment_url = media_playlist_url.join(line).or(Err(format!("Invalid segment url '{}'", line)))?;
+        let bytes = client.get(segment_url).send().await.or(Err(String::from("Failed to GET segment")))?
+            .bytes().await.or(Err(String::from("Failed to read segment")))?;
+        file.write_all(&bytes).or(Err(String::from("Failed to write segment")))?;
+        sink.report(Progress { stage: Stage::Video, downloaded: (i + 1) as u64, total: total_segments });
+    }
+
+    sink.report(Progress { stage: Stage::Muxing, downloaded: 0, total: 0 });
+    let out_name = out_path.file_name().unwrap().to_str().unwrap();
+    let muxed = remux_to_mp4(out_name, path)?;
+    sink.report(Progress { stage: Stage::Muxing, downloaded: 1, total: 1 });
+    Ok(muxed)
+}
+
+/// Picks the highest-bandwidth `#EXT-X-STREAM-INF` variant from a master
+/// HLS playlist.
+fn select_variant(base: &Url, master: &str) -> Result<Url, String> {
+    let mut best_bandwidth = 0u64;
+    let mut best_uri: Option<String> = None;
+    let mut lines = master.lines().peekable();
+
+    while let Some(line) = lines.next() {
+        if !line.starts_with("#EXT-X-STREAM-INF:") {
+            continue;
+        }
+        let bandwidth = line.split("BANDWIDTH=").nth(1)
+            .and_then(|rest| rest.split(',').next())
+            .and_then(|b| b.trim().parse::<u64>().ok())
+            .unwrap_or(0);
+        if let Some(uri) = lines.peek() {
+            if bandwidth >= best_bandwidth {
+                best_bandwidth = bandwidth;
+                best_uri = Some(uri.trim().to_string());
+            }
+        }
+    }
+
+    match best_uri {
+        Some(uri) => base.join(&uri).or(Err(format!("Invalid variant url '{}'", uri))),
+        None => {
+            warn!("no #EXT-X-STREAM-INF variants found, treating as a media playlist");
+            Ok(base.clone())
+        }
+    }
+}
+
+fn mux_av(video_path: &PathBuf, audio_path: &PathBuf, path: &PathBuf) -> Result<String, String> {
+    let mut out_path = PathBuf::from(path);
+    out_path.push("video.mp4");
+
+    let child = Command::new("ffmpeg")
+        .arg("-y")
+        .arg("-i").arg(video_path)
+        .arg("-i").arg(audio_path)
+        .arg("-c:v").arg("copy")
+        .arg("-c:a").arg("copy")
+        .arg(&out_path)
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .or(Err(String::from("Failed to spawn ffmpeg")))?;
+
+    let output = child.wait_with_output().or(Err(String::from("ffmpeg did not run to completion")))?;
+    info!("status: {}", output.status);
+
+    let _ = fs::remove_file(video_path);
+    let _ = fs::remove_file(audio_path);
+
+    Ok(String::from(out_path.file_name().unwrap().to_str().unwrap()))
+}
+
+fn remux_to_mp4(segment_file: &str, path: &PathBuf) -> Result<String, String> {
+    let mut in_path = PathBuf::from(path);
+    in_path.push(segment_file);
+    let mut out_path = PathBuf::from(path);
+    out_path.push("video.mp4");
+
+    let child = Command::new("ffmpeg")
+        .arg("-y")
+        .arg("-i").arg(&in_path)
+        .arg("-c").arg("copy")
+        .arg(&out_path)
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .or(Err(String::from("Failed to spawn ffmpeg")))?;
+
+    let output = child.wait_with_output().or(Err(String::from("ffmpeg did not run to completion")))?;
+    info!("status: {}", output.status);
+
+    let _ = fs::remove_file(&in_path);
+
+    Ok(String::from(out_path.file_name().unwrap().to_str().unwrap()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE_MPD: &str = r#"<?xml version="1.0"?>
+<MPD>
+  <Period>
+    <AdaptationSet mimeType="video/mp4">
+      <Representation id="v0" bandwidth="500000" height="360">
+        <SegmentTemplate media="v0-$Number$.m4s" initialization="v0-init.m4s" startNumber="1">
+          <SegmentTimeline>
+            <S d="2000" r="1"/>
+            <S d="2000"/>
+          </SegmentTimeline>
+        </SegmentTemplate>
+      </Representation>
+      <Representation id="v1" bandwidth="1000000" height="720">
+        <SegmentTemplate media="v1-$Number$.m4s" initialization="v1-init.m4s" startNumber="1">
+          <SegmentTimeline>
+            <S d="2000" r="4"/>
+          </SegmentTimeline>
+        </SegmentTemplate>
+      </Representation>
+    </AdaptationSet>
+    <AdaptationSet mimeType="audio/mp4">
+      <Representation id="a0" bandwidth="128000">
+        <SegmentTemplate media="a0-$Number$.m4s" initialization="a0-init.m4s" startNumber="1">
+          <SegmentTimeline>
+            <S d="2000" r="2"/>
+          </SegmentTimeline>
+        </SegmentTemplate>
+      </Representation>
+    </AdaptationSet>
+  </Period>
+</MPD>"#;
+
+    #[test]
+    fn parse_mpd_scopes_segment_count_per_representation() {
+        let representations = parse_mpd(FIXTURE_MPD).unwrap();
+        assert_eq!(representations.len(), 3);
+
+        let v0 = &representations[0];
+        assert_eq!(v0.media_template, "v0-$Number$.m4s");
+        assert_eq!(v0.segment_count, 3);
+
+        let v1 = &representations[1];
+        assert_eq!(v1.media_template, "v1-$Number$.m4s");
+        assert_eq!(v1.segment_count, 5);
+
+        let a0 = &representations[2];
+        assert_eq!(a0.mime_type, "audio/mp4");
+        assert_eq!(a0.media_template, "a0-$Number$.m4s");
+        assert_eq!(a0.segment_count, 3);
+    }
+}
@@ -0,0 +1,34 @@
+/// A stage of the download pipeline, reported alongside byte counts so a
+/// progress sink can label what it's showing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Video,
+    Muxing,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Progress {
+    pub stage: Stage,
+    pub downloaded: u64,
+    pub total: u64,
+}
+
+/// Where download progress gets reported. The CLI supplies an `indicatif`
+/// bar; anything embedding this crate can supply its own — a GUI progress
+/// widget, an `mpsc` sender, whatever fits.
+pub trait ProgressSink: Send + Sync {
+    fn report(&self, progress: Progress);
+}
+
+impl<F: Fn(Progress) + Send + Sync> ProgressSink for F {
+    fn report(&self, progress: Progress) {
+        self(progress)
+    }
+}
+
+/// A sink that reports nothing, for callers that don't care.
+pub struct NoopProgress;
+
+impl ProgressSink for NoopProgress {
+    fn report(&self, _progress: Progress) {}
+}
@@ -0,0 +1,140 @@
+use std::{fs, path::PathBuf, str::FromStr};
+
+use log::info;
+use subparse::{timetypes::{TimePoint, TimeSpan}, SrtFile, SubtitleFileInterface};
+
+/// How far past a talk's last transcript cue to hold a trailing-cue open
+/// for, when no next cue's start time is available to derive it from.
+const DEFAULT_TRAILING_CUE_MS: i64 = 3000;
+
+/// A rough chapter every five minutes of runtime, when `--chapters` is set.
+const CHAPTER_INTERVAL_MS: i64 = 5 * 60 * 1000;
+
+/// Subtitle container to emit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubFormat {
+    Srt,
+    Vtt,
+}
+
+impl Default for SubFormat {
+    fn default() -> Self {
+        SubFormat::Srt
+    }
+}
+
+impl FromStr for SubFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "srt" => Ok(SubFormat::Srt),
+            "vtt" => Ok(SubFormat::Vtt),
+            other => Err(format!("invalid subtitle format '{}': expected srt or vtt", other)),
+        }
+    }
+}
+
+impl SubFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            SubFormat::Srt => "srt",
+            SubFormat::Vtt => "vtt",
+        }
+    }
+}
+
+/// A chapter marker derived from the transcript, ready to hand to ffmpeg's
+/// `ffmetadata` chapter format.
+pub struct Chapter {
+    pub start_ms: i64,
+    pub end_ms: i64,
+    pub title: String,
+}
+
+/// Generate a subtitle file at `path` in `format`. Each cue's end time is
+/// the next cue's start time, or `trailing_cue_ms` past its own start for
+/// the final cue.
+pub fn generate_subtitle_file(path: &PathBuf, times: &Vec<i64>, texts: &Vec<String>, format: SubFormat, trailing_cue_ms: i64) {
+    info!("generating subtitle");
+    if path.exists() { return }
+
+    let cues = build_cues(times, texts, trailing_cue_ms);
+    match format {
+        SubFormat::Srt => write_srt(path, cues),
+        SubFormat::Vtt => write_vtt(path, cues),
+    }
+}
+
+fn build_cues(times: &Vec<i64>, texts: &Vec<String>, trailing_cue_ms: i64) -> Vec<(TimeSpan, String)> {
+    let mut cues = vec![];
+    for (i, text) in texts.iter().enumerate() {
+        let start_time = *times.get(i).unwrap();
+        let end_time = times.get(i + 1).copied().unwrap_or(start_time + trailing_cue_ms);
+
+        cues.push((
+            TimeSpan::new(TimePoint::from_msecs(start_time), TimePoint::from_msecs(end_time)),
+            String::from(text),
+        ));
+    }
+    cues
+}
+
+fn write_srt(path: &PathBuf, cues: Vec<(TimeSpan, String)>) {
+    let file = SrtFile::create(cues).unwrap();
+    let data = file.to_data().unwrap();
+    fs::write(path, data).expect("Unable to write file");
+}
+
+fn write_vtt(path: &PathBuf, cues: Vec<(TimeSpan, String)>) {
+    let mut data = String::from("WEBVTT\n\n");
+    for (span, text) in cues {
+        data.push_str(&format!(
+            "{} --> {}\n{}\n\n",
+            format_vtt_timestamp(span.start),
+            format_vtt_timestamp(span.end),
+            text,
+        ));
+    }
+    fs::write(path, data).expect("Unable to write file");
+}
+
+fn format_vtt_timestamp(point: TimePoint) -> String {
+    let total_ms = point.msecs();
+    let hours = total_ms / 3_600_000;
+    let minutes = (total_ms / 60_000) % 60;
+    let seconds = (total_ms / 1000) % 60;
+    let millis = total_ms % 1000;
+    format!("{:02}:{:02}:{:02}.{:03}", hours, minutes, seconds, millis)
+}
+
+/// Derive coarse chapter markers from the transcript: one chapter every
+/// [`CHAPTER_INTERVAL_MS`] of runtime, titled from the first cue at or
+/// after that boundary. The final chapter's end is the last cue's start
+/// plus `trailing_cue_ms`, the same fallback `build_cues` uses for the
+/// final subtitle cue, so a talk whose last cue lands on a fresh chapter
+/// boundary doesn't end up with a zero-duration final chapter.
+pub fn derive_chapters(times: &Vec<i64>, texts: &Vec<String>, trailing_cue_ms: i64) -> Vec<Chapter> {
+    let mut starts = vec![];
+    let mut next_boundary = 0;
+
+    for (i, &time) in times.iter().enumerate() {
+        if time < next_boundary {
+            continue;
+        }
+        let title = texts.get(i).cloned().unwrap_or_else(|| format!("Chapter {}", starts.len() + 1));
+        starts.push((time, title));
+        next_boundary = time + CHAPTER_INTERVAL_MS;
+    }
+
+    let last_end = times.last().copied().unwrap_or(0) + trailing_cue_ms;
+    starts.iter().enumerate().map(|(i, (start, title))| {
+        let end = starts.get(i + 1).map(|(next_start, _)| *next_start).unwrap_or(last_end);
+        Chapter { start_ms: *start, end_ms: end, title: title.clone() }
+    }).collect()
+}
+
+/// Default trailing-cue duration, used when the CLI doesn't override it.
+pub fn default_trailing_cue_ms() -> i64 {
+    DEFAULT_TRAILING_CUE_MS
+}